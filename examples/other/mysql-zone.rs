@@ -1,20 +1,22 @@
-//! MySQL backed zone serving minimal proof of concept.
+//! SQL backed zone serving minimal proof of concept.
 //
 // This example extends `domain` with a new `ZoneStore` impl adding support for
-// MySQL backed zones. This demonstration only implements the `ReadableZone`
-// trait, it doesn't implement the `WritableZone` trait, so database access is
-// read-only. Write access could be implemented, it just isn't in this
-// example. The same approach can be used to implement access for any kind of
-// backed, e.g. invoking shell commands to get the answers even ;-)
+// SQL backed zones, read from (and, via `WritableZone`, written to) the
+// generic PowerDNS backend schema. It's driven through sqlx's `Any` database
+// abstraction, so the same code works against MySQL, PostgreSQL or SQLite:
+// the driver is picked at runtime from the `DATABASE_URL` scheme
+// (`mysql://`, `postgres://` or `sqlite://`). The instructions below are
+// written for the MySQL schema, since that's what PowerDNS documents best,
+// but the generic schemas for PostgreSQL and SQLite work the same way.
 //
 // Warning: This example needs a lot of setup and has several prerequisites.
 //
 // ===========================================================================
-// A big shout out to PowerDNS as this example uses their MySQL database
-// schema and their zone2sql tool. And also to the sqlx project for making
+// A big shout out to PowerDNS as this example uses their generic database
+// schemas and their zone2sql tool. And also to the sqlx project for making
 // database access via Rust so easy.
 //
-// For more information about the PowerDNS MySQL support see:
+// For more information about the PowerDNS generic SQL backends see:
 //   https://doc.powerdns.com/authoritative/backends/generic-mysql.html
 //
 // For more information about SQLX see: https://github.com/launchbadge/sqlx
@@ -116,29 +118,43 @@
 // 1.75.0 which exceeds our Rust MSRV of 1.67.0.
 
 use std::future::Future;
+use std::io;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
 
 use bytes::Bytes;
-use sqlx::mysql::MySqlConnectOptions;
-use sqlx::MySqlPool;
+use sqlx::any::AnyConnectOptions;
+use sqlx::AnyPool;
 use sqlx::Row;
+use tokio::sync::Mutex as AsyncMutex;
 
 use domain::base::iana::{Class, Rcode};
+use domain::base::name::Label;
+use domain::base::opt::rfc7871::ClientSubnet;
 use domain::base::scan::IterScanner;
 use domain::base::{Name, Rtype, Ttl};
 use domain::rdata::ZoneRecordData;
 use domain::zonetree::error::OutOfZone;
 use domain::zonetree::types::StoredName;
 use domain::zonetree::{
-    Answer, ReadableZone, Rrset, SharedRrset, WalkOp, WritableZone, Zone,
-    ZoneStore, ZoneTree,
+    Answer, ReadableZone, Rrset, SharedRrset, WalkOp, WritableZone,
+    WritableZoneNode, Zone, ZoneStore, ZoneTree,
 };
 
 #[path = "../common/serve-utils.rs"]
 mod common;
 
+// Read-path queries, kept as named constants (rather than inlined at each
+// call site) so that every call reuses byte-identical SQL text. sqlx's
+// per-connection statement cache is keyed on that text, so this is what
+// lets a high-QPS server reuse an already-prepared statement instead of
+// re-preparing on every query; see `DatabaseZoneOptions`.
+const SELECT_RRSET_SQL: &str = r#"SELECT R.content, R.ttl FROM domains D, records R WHERE D.name = ? AND D.id = R.domain_id AND R.name = ? AND R.type = ?"#;
+const SELECT_EXISTS_SQL: &str = r#"SELECT 1 FROM domains D, records R WHERE D.name = ? AND D.id = R.domain_id AND R.name = ? LIMIT 1"#;
+const SELECT_ECS_RULES_SQL: &str = r#"SELECT E.content, E.ttl, E.netmask, E.network FROM domains D, ecs_rules E WHERE D.name = ? AND D.id = E.domain_id AND E.name = ? AND E.type = ?"#;
+const SELECT_WALK_SQL: &str = r#"SELECT R.name, R.type AS rtype, R.content, R.ttl FROM domains D, records R WHERE D.name = ? AND D.id = R.domain_id"#;
+
 #[tokio::main]
 async fn main() {
     // Create a zone whose queries will be satisfied by querying the database
@@ -157,7 +173,7 @@ async fn main() {
     //   2. Get a read interface to it via `.read()`.
     //   3. Query the zone, synchronously or asynchronously, based on what
     //      the zone says it supports. For stock `domain` zones the
-    //      `.is_async()` call will return false, but for our MySQL backed
+    //      `.is_async()` call will return false, but for our SQL backed
     //      zone it returns true, as the DB calls are asynchronous.
     let zone = zones.find_zone(&qname, qclass).unwrap().read();
     let zone_answer = match zone.is_async() {
@@ -170,6 +186,115 @@ async fn main() {
     let wire_response =
         common::generate_wire_response(&wire_query, zone_answer);
     common::print_dig_style_response(&wire_query, &wire_response, false);
+
+    // Split-horizon / geo answering, driven by an EDNS Client Subnet
+    // option, isn't reachable through `ZoneTree`/`ReadableZone` above (see
+    // `query_async_ecs`'s doc comment), so exercise it against a
+    // `DatabaseReadZone` obtained directly instead.
+    let read_zone = DatabaseZoneBuilder::mk_read_zone(
+        "example.com",
+        DatabaseZoneOptions::default(),
+    )
+    .await;
+    let ecs = ClientSubnet::try_new(24, 0, "192.0.2.1".parse().unwrap())
+        .unwrap();
+    let (ecs_answer, scope_prefix_len) = read_zone
+        .query_async_ecs(qname.clone(), qtype, Some(ecs))
+        .await
+        .unwrap();
+    let ecs_wire_response =
+        common::generate_wire_response(&wire_query, ecs_answer);
+    println!(
+        "--- ECS split-horizon answer (SCOPE PREFIX-LENGTH {scope_prefix_len}) ---"
+    );
+    common::print_dig_style_response(&wire_query, &ecs_wire_response, false);
+}
+
+//----------- SqlDialect ------------------------------------------------------
+
+// Which bind-parameter syntax the connected driver expects. MySQL and
+// SQLite both accept positional `?` placeholders (the syntax every query
+// constant in this module is written with), but native PostgreSQL only
+// understands `$1, $2, ...` -- sqlx's `Any` abstraction picks a driver at
+// runtime but does not translate between the two, so a query issued
+// as-is against a `postgres://` pool is a syntax error. Every query goes
+// through `SqlDialect::rewrite` just before it's prepared instead.
+#[derive(Clone, Copy, Debug)]
+enum SqlDialect {
+    QuestionMark,
+    Postgres,
+}
+
+impl SqlDialect {
+    fn from_database_url(database_url: &str) -> Self {
+        if database_url.starts_with("postgres:")
+            || database_url.starts_with("postgresql:")
+        {
+            SqlDialect::Postgres
+        } else {
+            SqlDialect::QuestionMark
+        }
+    }
+
+    // Rewrites `sql`'s `?` placeholders into Postgres's `$1, $2, ...`
+    // form; every other dialect is handed back unchanged. None of this
+    // module's query text contains a literal `?` outside of a
+    // placeholder, so a plain left-to-right walk is enough.
+    fn rewrite<'a>(&self, sql: &'a str) -> std::borrow::Cow<'a, str> {
+        match self {
+            SqlDialect::QuestionMark => std::borrow::Cow::Borrowed(sql),
+            SqlDialect::Postgres => {
+                let mut out = String::with_capacity(sql.len() + 8);
+                let mut n = 0u32;
+                for c in sql.chars() {
+                    if c == '?' {
+                        n += 1;
+                        out.push('$');
+                        out.push_str(&n.to_string());
+                    } else {
+                        out.push(c);
+                    }
+                }
+                std::borrow::Cow::Owned(out)
+            }
+        }
+    }
+}
+
+//----------- DatabaseZoneOptions ---------------------------------------------
+
+// Pool tuning knobs for [`DatabaseZoneBuilder`]. The defaults are
+// conservative enough for the proof of concept; high-QPS serving will
+// generally want a bigger `max_connections` and a longer `idle_timeout` so
+// connections -- and the server-side prepared statements cached on them --
+// survive between bursts of traffic.
+#[derive(Clone, Debug)]
+pub struct DatabaseZoneOptions {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: std::time::Duration,
+    pub idle_timeout: Option<std::time::Duration>,
+
+    // Every query in this module is issued with the same, constant SQL
+    // text (see e.g. `SELECT_RRSET_SQL` above), so sqlx's per-connection
+    // prepared-statement cache already reuses one server-side prepared
+    // statement per query shape rather than re-preparing on every call.
+    // This lets callers size that cache. It's only meaningful for
+    // MySQL/PostgreSQL -- see `DatabaseZoneBuilder::connect`, which sets it
+    // through each driver's own `statement_cache_capacity` builder method.
+    pub statement_cache_capacity: usize,
+}
+
+impl Default for DatabaseZoneOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout: std::time::Duration::from_secs(30),
+            idle_timeout: Some(std::time::Duration::from_secs(10 * 60)),
+            statement_cache_capacity: 100,
+        }
+    }
 }
 
 //----------- DatbaseZoneBuilder ---------------------------------------------
@@ -178,26 +303,101 @@ pub struct DatabaseZoneBuilder;
 
 impl DatabaseZoneBuilder {
     pub async fn mk_test_zone(apex_name: &str) -> Zone {
-        let opts: MySqlConnectOptions =
-            std::env::var("DATABASE_URL").unwrap().parse().unwrap();
-        let pool = MySqlPool::connect_with(opts).await.unwrap();
+        Self::mk_zone(apex_name, DatabaseZoneOptions::default()).await
+    }
+
+    pub async fn mk_zone(
+        apex_name: &str,
+        options: DatabaseZoneOptions,
+    ) -> Zone {
+        let (pool, dialect) = Self::connect(&options).await;
         let apex_name = StoredName::from_str(apex_name).unwrap();
-        let node = DatabaseNode::new(pool, apex_name);
+        let node = DatabaseNode::new(pool, dialect, apex_name);
         Zone::new(node)
     }
+
+    // An ECS-carrying counterpart to `mk_zone`. `ZoneStore::read()` returns
+    // `Box<dyn ReadableZone>`, and that trait doesn't have a SCOPE
+    // PREFIX-LENGTH-carrying query method or a way to downcast back to the
+    // concrete `DatabaseReadZone` -- see the comment on `query_async_ecs`
+    // itself. So callers who want split-horizon answering need a
+    // `DatabaseReadZone` directly, bypassing `Zone`/`ZoneTree`, which is
+    // what this constructs.
+    pub async fn mk_read_zone(
+        apex_name: &str,
+        options: DatabaseZoneOptions,
+    ) -> DatabaseReadZone {
+        let (pool, dialect) = Self::connect(&options).await;
+        let apex_name = StoredName::from_str(apex_name).unwrap();
+        DatabaseReadZone::new(pool, dialect, apex_name)
+    }
+
+    // Registers the MySQL/PostgreSQL/SQLite drivers that `AnyPool` picks
+    // between based on the `DATABASE_URL` scheme, then connects a pool
+    // sized per `options`, alongside the `SqlDialect` that scheme implies.
+    // Shared by `mk_zone` and `mk_read_zone`, which only differ in what
+    // they wrap the resulting pool in.
+    async fn connect(
+        options: &DatabaseZoneOptions,
+    ) -> (sqlx::AnyPool, SqlDialect) {
+        sqlx::any::install_default_drivers();
+
+        let database_url = std::env::var("DATABASE_URL").unwrap();
+        let dialect = SqlDialect::from_database_url(&database_url);
+        // `statement_cache_capacity` isn't something `AnyConnectOptions`
+        // exposes generically -- it's a typed builder method on each
+        // driver's own connect-options type. So, for just this one knob,
+        // step outside the `Any` abstraction: parse the URL as the
+        // concrete driver its scheme names, set the cache size there, and
+        // convert back into `AnyConnectOptions` to hand to `AnyPool`.
+        // SQLite has no server-side prepared-statement cache to size, so
+        // there's nothing to set in that case.
+        let opts: AnyConnectOptions = if database_url
+            .starts_with("mysql:")
+        {
+            sqlx::mysql::MySqlConnectOptions::from_str(&database_url)
+                .unwrap()
+                .statement_cache_capacity(options.statement_cache_capacity)
+                .into()
+        } else if database_url.starts_with("postgres:")
+            || database_url.starts_with("postgresql:")
+        {
+            sqlx::postgres::PgConnectOptions::from_str(&database_url)
+                .unwrap()
+                .statement_cache_capacity(options.statement_cache_capacity)
+                .into()
+        } else {
+            database_url.parse().unwrap()
+        };
+
+        let pool = sqlx::any::AnyPoolOptions::new()
+            .max_connections(options.max_connections)
+            .min_connections(options.min_connections)
+            .acquire_timeout(options.acquire_timeout)
+            .idle_timeout(options.idle_timeout)
+            .connect_with(opts)
+            .await
+            .unwrap();
+        (pool, dialect)
+    }
 }
 
 //----------- DatbaseNode ----------------------------------------------------
 
 #[derive(Debug)]
 struct DatabaseNode {
-    db_pool: sqlx::MySqlPool,
+    db_pool: sqlx::AnyPool,
+    dialect: SqlDialect,
     apex_name: StoredName,
 }
 
 impl DatabaseNode {
-    fn new(db_pool: sqlx::MySqlPool, apex_name: StoredName) -> Self {
-        Self { db_pool, apex_name }
+    fn new(
+        db_pool: sqlx::AnyPool,
+        dialect: SqlDialect,
+        apex_name: StoredName,
+    ) -> Self {
+        Self { db_pool, dialect, apex_name }
     }
 }
 
@@ -215,6 +415,7 @@ impl ZoneStore for DatabaseNode {
     fn read(self: Arc<Self>) -> Box<dyn ReadableZone> {
         Box::new(DatabaseReadZone::new(
             self.db_pool.clone(),
+            self.dialect,
             self.apex_name.clone(),
         ))
     }
@@ -222,20 +423,323 @@ impl ZoneStore for DatabaseNode {
     fn write(
         self: Arc<Self>,
     ) -> Pin<Box<dyn Future<Output = Box<dyn WritableZone>>>> {
-        todo!()
+        let db_pool = self.db_pool.clone();
+        let dialect = self.dialect;
+        let apex_name = self.apex_name.clone();
+        Box::pin(async move {
+            Box::new(DatabaseWriteZone::new(db_pool, dialect, apex_name))
+                as Box<dyn WritableZone>
+        })
     }
 }
 
 //----------- DatbaseReadZone ------------------------------------------------
 
-struct DatabaseReadZone {
-    db_pool: sqlx::MySqlPool,
+// `pub` so that `DatabaseZoneBuilder::mk_read_zone` can hand one back to a
+// caller that wants `query_async_ecs`; see that method's doc comment.
+pub struct DatabaseReadZone {
+    db_pool: sqlx::AnyPool,
+    dialect: SqlDialect,
     apex_name: StoredName,
 }
 
 impl DatabaseReadZone {
-    fn new(db_pool: sqlx::MySqlPool, apex_name: StoredName) -> Self {
-        Self { db_pool, apex_name }
+    fn new(
+        db_pool: sqlx::AnyPool,
+        dialect: SqlDialect,
+        apex_name: StoredName,
+    ) -> Self {
+        Self { db_pool, dialect, apex_name }
+    }
+
+    // Fetches every row for `(apex_name, owner, rtype)` and folds them into
+    // a single `Rrset`, so that e.g. multiple `A` records or `MX` priorities
+    // all end up in the one answer instead of only the first row found.
+    async fn fetch_rrset(
+        db_pool: &sqlx::AnyPool,
+        dialect: SqlDialect,
+        apex_name: &str,
+        owner: &str,
+        rtype: Rtype,
+    ) -> Option<Rrset> {
+        let rows = sqlx::query(&dialect.rewrite(SELECT_RRSET_SQL))
+        .bind(apex_name)
+        .bind(owner)
+        .bind(rtype.to_string())
+        .fetch_all(db_pool)
+        .await
+        .ok()?;
+
+        let mut rrset: Option<Rrset> = None;
+        for row in rows {
+            let ttl = row.try_get("ttl").unwrap();
+            let content: String = row.try_get("content").unwrap();
+            let content_strings = content.split_ascii_whitespace().collect::<std::vec::Vec<&str>>();
+            let mut scanner = IterScanner::new(&content_strings);
+            match ZoneRecordData::scan(rtype, &mut scanner) {
+                Ok(data) => {
+                    rrset
+                        .get_or_insert_with(|| {
+                            Rrset::new(rtype, Ttl::from_secs(ttl))
+                        })
+                        .push_data(data);
+                }
+                Err(err) => {
+                    eprintln!(
+                        "Unable to parse DB record of type {rtype}: {err}"
+                    );
+                }
+            }
+        }
+        rrset
+    }
+
+    // Cheap existence check used to tell NODATA (the name exists, just not
+    // with this type) apart from NXDOMAIN (the name doesn't exist at all).
+    async fn name_exists(
+        db_pool: &sqlx::AnyPool,
+        dialect: SqlDialect,
+        apex_name: &str,
+        owner: &str,
+    ) -> bool {
+        sqlx::query(&dialect.rewrite(SELECT_EXISTS_SQL))
+        .bind(apex_name)
+        .bind(owner)
+        .fetch_optional(db_pool)
+        .await
+        .ok()
+        .flatten()
+        .is_some()
+    }
+
+    // Resolves `owner`/`qtype`, chasing CNAMEs (RFC 1034 section 3.6.2)
+    // along the way: if `owner` has a CNAME record the real answer lives
+    // at the CNAME's target instead, so a real authoritative server walks
+    // the chain and returns every CNAME RRset it passed through alongside
+    // the RRset (or negative result) the chain finally ends on.
+    async fn lookup(
+        db_pool: &sqlx::AnyPool,
+        dialect: SqlDialect,
+        apex_name: &str,
+        owner: &str,
+        qtype: Rtype,
+    ) -> Answer {
+        let mut chain = std::vec::Vec::new();
+        let mut current_owner = owner.to_string();
+        let mut seen = std::collections::HashSet::new();
+        seen.insert(current_owner.clone());
+
+        // A CNAME shadows every other type at its owner (except at the
+        // zone apex, which PowerDNS's schema never stores a CNAME for
+        // anyway), so it takes priority over the requested type. Stop
+        // after a handful of hops rather than chasing forever if CNAMEs
+        // point at each other in a loop.
+        while qtype != Rtype::CNAME && chain.len() < 8 {
+            let Some(cname) = Self::fetch_rrset(
+                db_pool,
+                dialect,
+                apex_name,
+                &current_owner,
+                Rtype::CNAME,
+            )
+            .await
+            else {
+                break;
+            };
+            let Some(target) =
+                cname.iter().next().map(|data| data.to_string())
+            else {
+                break;
+            };
+            chain.push(cname);
+            if !seen.insert(target.clone()) {
+                break;
+            }
+            current_owner = target;
+        }
+
+        let final_rrset = Self::fetch_rrset(
+            db_pool,
+            dialect,
+            apex_name,
+            &current_owner,
+            qtype,
+        )
+        .await;
+        let is_negative = final_rrset.is_none();
+        let rcode = if final_rrset.is_some()
+            || Self::name_exists(db_pool, dialect, apex_name, &current_owner)
+                .await
+        {
+            // Either a hit, or NODATA: the owner exists, just not with
+            // this type.
+            Rcode::NOERROR
+        } else {
+            Rcode::NXDOMAIN
+        };
+
+        let mut answer = Answer::new(rcode);
+        for cname in chain {
+            answer.add_answer(SharedRrset::new(cname));
+        }
+        if let Some(rrset) = final_rrset {
+            answer.add_answer(SharedRrset::new(rrset));
+        }
+
+        // Negative responses -- both NODATA and NXDOMAIN -- carry the
+        // zone's SOA in the authority section (RFC 2308), so resolvers
+        // know how long they may cache the non-existence.
+        if is_negative {
+            if let Some(soa) = Self::fetch_rrset(
+                db_pool,
+                dialect,
+                apex_name,
+                apex_name,
+                Rtype::SOA,
+            )
+            .await
+            {
+                answer.add_authority(SharedRrset::new(soa));
+            }
+        }
+
+        answer
+    }
+
+    // Split-horizon / geo answering driven by an EDNS Client Subnet (RFC
+    // 7871) option, the way PowerDNS's geo backend varies its answer by
+    // client network. Rules are matched most-specific prefix first against
+    // an `ecs_rules` table (domain_id, name, type, network, netmask,
+    // content, ttl) alongside the ordinary `records` table: every rule for
+    // this owner/type is fetched, and the ones whose `network`/`netmask`
+    // actually contain the client address -- checked as a real bitwise
+    // prefix comparison, not string equality, since a rule covering e.g.
+    // 192.0.2.0/16 must match a query address truncated by the resolver
+    // to a /24 -- compete on longest prefix.
+    //
+    // Returns the answer together with the SCOPE PREFIX-LENGTH: the number
+    // of client-address bits actually used to pick it. That's 0 when no
+    // rule matched (the answer is the same for every client), or the
+    // matched rule's netmask otherwise -- capped at the query's SOURCE
+    // PREFIX-LENGTH, since we must never claim to have used more of the
+    // client address than the resolver was willing to send us. Callers
+    // that cache this answer need to fold those SCOPE PREFIX-LENGTH bits
+    // of the client address into their cache key, or they will serve a
+    // network-specific answer to the wrong clients.
+    async fn lookup_ecs(
+        db_pool: &sqlx::AnyPool,
+        dialect: SqlDialect,
+        apex_name: &str,
+        owner: &str,
+        qtype: Rtype,
+        ecs: Option<ClientSubnet>,
+    ) -> (Answer, u8) {
+        let Some(ecs) = ecs else {
+            return (
+                Self::lookup(db_pool, dialect, apex_name, owner, qtype).await,
+                0,
+            );
+        };
+
+        let Ok(client_addr) =
+            std::net::IpAddr::from_str(&ecs.addr().to_string())
+        else {
+            return (
+                Self::lookup(db_pool, dialect, apex_name, owner, qtype).await,
+                0,
+            );
+        };
+
+        let rows = sqlx::query(&dialect.rewrite(SELECT_ECS_RULES_SQL))
+            .bind(apex_name)
+            .bind(owner)
+            .bind(qtype.to_string())
+            .fetch_all(db_pool)
+            .await
+            .unwrap_or_default();
+
+        // Only a rule whose netmask is no longer than the bits the
+        // resolver actually sent can be checked for containment at all --
+        // anything beyond `source_prefix_len` isn't client information
+        // (`ClientSubnet::parse` zeroes it; `new`/`try_new` keep whatever
+        // the address held there). Among the rules that do contain the
+        // client address, the one with the longest (most specific)
+        // netmask wins.
+        let best = rows
+            .into_iter()
+            .filter_map(|row| {
+                let netmask: u32 = row.try_get("netmask").ok()?;
+                let netmask = netmask as u8;
+                let network: String = row.try_get("network").ok()?;
+                let network = std::net::IpAddr::from_str(&network).ok()?;
+                if netmask <= ecs.source_prefix_len()
+                    && prefix_matches(client_addr, network, netmask)
+                {
+                    Some((netmask, row))
+                } else {
+                    None
+                }
+            })
+            .max_by_key(|(netmask, _)| *netmask);
+
+        let Some((netmask, row)) = best else {
+            // No network-specific rule contains the client: fall back to
+            // the ordinary, network-independent answer.
+            return (
+                Self::lookup(db_pool, dialect, apex_name, owner, qtype).await,
+                0,
+            );
+        };
+
+        let ttl = row.try_get("ttl").unwrap();
+        let scope_prefix_len = netmask.min(ecs.source_prefix_len());
+        let content: String = row.try_get("content").unwrap();
+        let content_strings = content
+            .split_ascii_whitespace()
+            .collect::<std::vec::Vec<&str>>();
+        let mut scanner = IterScanner::new(&content_strings);
+        match ZoneRecordData::scan(qtype, &mut scanner) {
+            Ok(data) => {
+                let mut rrset = Rrset::new(qtype, Ttl::from_secs(ttl));
+                rrset.push_data(data);
+                let mut answer = Answer::new(Rcode::NOERROR);
+                answer.add_answer(SharedRrset::new(rrset));
+                (answer, scope_prefix_len)
+            }
+            Err(err) => {
+                eprintln!("Unable to parse DB record of type {qtype}: {err}");
+                (Answer::new(Rcode::SERVFAIL), 0)
+            }
+        }
+    }
+
+    // An ECS-carrying counterpart to `query_async`. It isn't part of
+    // `ReadableZone` -- that trait, and `Answer` itself, live in
+    // `domain::zonetree` and don't yet carry a SCOPE PREFIX-LENGTH out of
+    // the zone-query path, so wiring this all the way through
+    // `ZoneStore::read()` -> `Box<dyn ReadableZone>` would mean extending
+    // those types rather than just this backend. In the meantime, a
+    // caller that wants this reaches `DatabaseReadZone` directly through
+    // `DatabaseZoneBuilder::mk_read_zone` instead of going through
+    // `Zone`/`ZoneTree`, which erase the concrete type; see `main` above.
+    pub fn query_async_ecs(
+        &self,
+        qname: Name<Bytes>,
+        qtype: Rtype,
+        ecs: Option<ClientSubnet>,
+    ) -> Pin<Box<dyn Future<Output = Result<(Answer, u8), OutOfZone>> + Send>>
+    {
+        let db_pool = self.db_pool.clone();
+        let dialect = self.dialect;
+        let apex_name = self.apex_name.to_string();
+        let fut = async move {
+            let owner = qname.to_string();
+            Ok(Self::lookup_ecs(
+                &db_pool, dialect, &apex_name, &owner, qtype, ecs,
+            )
+            .await)
+        };
+        Box::pin(fut)
     }
 }
 
@@ -252,37 +756,13 @@ impl ReadableZone for DatabaseReadZone {
         qtype: Rtype,
     ) -> Pin<Box<dyn Future<Output = Result<Answer, OutOfZone>> + Send>> {
         let db_pool = self.db_pool.clone();
+        let dialect = self.dialect;
         let apex_name = self.apex_name.to_string();
         let fut = async move {
-            let answer = if let Ok(row) = sqlx::query(
-                r#"SELECT R.content, R.ttl FROM domains D, records R WHERE D.name = ? AND D.id = R.domain_id AND R.name = ? AND R.type = ? LIMIT 1"#)
-            .bind(apex_name)
-            .bind(qname.to_string())
-            .bind(qtype.to_string())
-            .fetch_one(&db_pool)
-            .await
-            {
-                let mut answer = Answer::new(Rcode::NOERROR);
-                let ttl = row.try_get("ttl").unwrap();
-                let mut rrset = Rrset::new(qtype, Ttl::from_secs(ttl));
-                let content: String = row.try_get("content").unwrap();
-                let content_strings = content.split_ascii_whitespace().collect::<std::vec::Vec<&str>>();
-                let mut scanner = IterScanner::new(&content_strings);
-                match ZoneRecordData::scan(qtype, &mut scanner) {
-                    Ok(data) => {
-                        rrset.push_data(data);
-                        let rrset = SharedRrset::new(rrset);
-                        answer.add_answer(rrset);
-                        answer
-                    }
-                    Err(err) => {
-                        eprintln!("Unable to parse DB record of type {qtype}: {err}");
-                        Answer::new(Rcode::SERVFAIL)
-                    }
-                }
-            } else {
-                Answer::new(Rcode::NXDOMAIN)
-            };
+            let owner = qname.to_string();
+            let answer =
+                Self::lookup(&db_pool, dialect, &apex_name, &owner, qtype)
+                    .await;
             Ok(answer)
         };
         Box::pin(fut)
@@ -293,10 +773,10 @@ impl ReadableZone for DatabaseReadZone {
         op: WalkOp,
     ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
         let db_pool = self.db_pool.clone();
+        let dialect = self.dialect;
         let apex_name = self.apex_name.to_string();
         let fut = async move {
-            for row in sqlx::query(
-                r#"SELECT R.name, R.type AS rtype, R.content, R.ttl FROM domains D, records R WHERE D.name = ? AND D.id = R.domain_id"#)
+            for row in sqlx::query(&dialect.rewrite(SELECT_WALK_SQL))
             .bind(apex_name)
             .fetch_all(&db_pool)
             .await
@@ -336,3 +816,357 @@ impl ReadableZone for DatabaseReadZone {
         unimplemented!()
     }
 }
+
+//----------- DatabaseWriteZone ----------------------------------------------
+
+// Persists updates made via `domain`'s zone-update path (e.g. RFC 2136
+// dynamic updates or AXFR/IXFR ingest) back into the PowerDNS `records`
+// table. All writes made while the zone is open happen inside a single
+// SQL transaction: `commit()` commits it, and if it is ever dropped
+// without being committed `sqlx::Transaction` rolls it back for us.
+struct DatabaseWriteZone {
+    db_pool: sqlx::AnyPool,
+    dialect: SqlDialect,
+    apex_name: StoredName,
+    txn: Arc<AsyncMutex<Option<sqlx::Transaction<'static, sqlx::Any>>>>,
+    domain_id: Arc<AsyncMutex<Option<i64>>>,
+}
+
+impl DatabaseWriteZone {
+    fn new(
+        db_pool: sqlx::AnyPool,
+        dialect: SqlDialect,
+        apex_name: StoredName,
+    ) -> Self {
+        Self {
+            db_pool,
+            dialect,
+            apex_name,
+            txn: Arc::new(AsyncMutex::new(None)),
+            domain_id: Arc::new(AsyncMutex::new(None)),
+        }
+    }
+}
+
+//--- impl WritableZone
+
+impl WritableZone for DatabaseWriteZone {
+    fn open(
+        &self,
+        _create_diff: bool,
+    ) -> Pin<
+        Box<dyn Future<Output = Result<Box<dyn WritableZoneNode>, io::Error>>>,
+    > {
+        let db_pool = self.db_pool.clone();
+        let dialect = self.dialect;
+        let apex_name = self.apex_name.clone();
+        let txn = self.txn.clone();
+        let domain_id = self.domain_id.clone();
+        Box::pin(async move {
+            let mut conn = db_pool.begin().await.map_err(to_io_err)?;
+
+            let domain_row = sqlx::query(
+                &dialect.rewrite(r#"SELECT id FROM domains WHERE name = ?"#),
+            )
+            .bind(apex_name.to_string())
+            .fetch_one(&mut conn)
+            .await
+            .map_err(to_io_err)?;
+            let id: i64 = domain_row.try_get("id").map_err(to_io_err)?;
+
+            *txn.lock().await = Some(conn);
+            *domain_id.lock().await = Some(id);
+
+            Ok(Box::new(DatabaseWriteNode {
+                dialect,
+                txn,
+                domain_id,
+                owner: apex_name,
+            }) as Box<dyn WritableZoneNode>)
+        })
+    }
+
+    fn commit(
+        &mut self,
+        bump_soa_serial: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<(), io::Error>>>> {
+        let dialect = self.dialect;
+        let apex_name = self.apex_name.to_string();
+        let domain_id = self.domain_id.clone();
+        let txn = self.txn.clone();
+        Box::pin(async move {
+            let mut guard = txn.lock().await;
+            let Some(mut conn) = guard.take() else {
+                return Ok(());
+            };
+            let id = domain_id.lock().await.expect("open() was called first");
+
+            if bump_soa_serial {
+                bump_soa_serial_in_txn(&mut conn, dialect, id, &apex_name)
+                    .await?;
+            }
+
+            conn.commit().await.map_err(to_io_err)
+        })
+    }
+}
+
+// Bumps the apex SOA serial as part of the given transaction, so that
+// secondaries polling via SOA queries or NOTIFY see the new serial at the
+// same time as (never before) the records it describes.
+async fn bump_soa_serial_in_txn(
+    conn: &mut sqlx::Transaction<'static, sqlx::Any>,
+    dialect: SqlDialect,
+    domain_id: i64,
+    apex_name: &str,
+) -> Result<(), io::Error> {
+    let row = sqlx::query(
+        &dialect.rewrite(r#"SELECT id, content FROM records WHERE domain_id = ? AND name = ? AND type = 'SOA'"#),
+    )
+    .bind(domain_id)
+    .bind(apex_name)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(to_io_err)?;
+
+    let record_id: i64 = row.try_get("id").map_err(to_io_err)?;
+    let content: String = row.try_get("content").map_err(to_io_err)?;
+    let mut fields: std::vec::Vec<&str> =
+        content.split_ascii_whitespace().collect();
+    let serial: u32 =
+        fields.get(2).and_then(|s| s.parse().ok()).unwrap_or(0);
+    let bumped = serial.wrapping_add(1).to_string();
+    if let Some(slot) = fields.get_mut(2) {
+        *slot = &bumped;
+    }
+    let new_content = fields.join(" ");
+
+    sqlx::query(&dialect.rewrite(r#"UPDATE records SET content = ? WHERE id = ?"#))
+        .bind(new_content)
+        .bind(record_id)
+        .execute(&mut **conn)
+        .await
+        .map_err(to_io_err)?;
+
+    Ok(())
+}
+
+fn to_io_err(err: sqlx::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+// Escapes `%`, `_` and the escape character itself so `input` can be
+// embedded in a SQL `LIKE ... ESCAPE '!'` pattern and matched literally.
+// `!` has no special meaning in a MySQL, PostgreSQL or SQLite string
+// literal, unlike `\`, which MySQL treats as its own string-literal escape
+// character under the default `sql_mode` (without `NO_BACKSLASH_ESCAPES`)
+// -- `ESCAPE '\'` is then parsed as an escaped quote and never terminates
+// the string, a SQL syntax error against MySQL specifically.
+fn escape_like(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if matches!(c, '!' | '%' | '_') {
+            escaped.push('!');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Whether `network/netmask` (an ECS rule's configured network) contains
+// `client`, checked as a real bitwise prefix comparison rather than
+// address equality. Addresses of different families never match.
+fn prefix_matches(
+    client: std::net::IpAddr,
+    network: std::net::IpAddr,
+    netmask: u8,
+) -> bool {
+    match (client, network) {
+        (std::net::IpAddr::V4(c), std::net::IpAddr::V4(n)) => {
+            masked_octets_eq(&c.octets(), &n.octets(), netmask)
+        }
+        (std::net::IpAddr::V6(c), std::net::IpAddr::V6(n)) => {
+            masked_octets_eq(&c.octets(), &n.octets(), netmask)
+        }
+        _ => false,
+    }
+}
+
+// `bits` ultimately comes from a DB row (`netmask`) compared against
+// `ClientSubnet::source_prefix_len()`, and the latter is only guaranteed
+// in range when the `ClientSubnet` was built through `try_new`/`parse`
+// rather than the still-unvalidated `new`. Bounds-check here too, rather
+// than trusting callers, so an oversized `bits` can't index past `a`/`b`.
+fn masked_octets_eq(a: &[u8], b: &[u8], bits: u8) -> bool {
+    let whole_bytes = usize::from(bits / 8);
+    if whole_bytes > a.len() || whole_bytes > b.len() {
+        return false;
+    }
+    if a[..whole_bytes] != b[..whole_bytes] {
+        return false;
+    }
+    let rem_bits = bits % 8;
+    if rem_bits == 0 {
+        return true;
+    }
+    let Some((&ao, &bo)) = a.get(whole_bytes).zip(b.get(whole_bytes)) else {
+        return false;
+    };
+    let mask = 0xffu8 << (8 - rem_bits);
+    (ao & mask) == (bo & mask)
+}
+
+//----------- DatabaseWriteNode -----------------------------------------------
+
+// One node of the zone tree being updated, identified by its owner name.
+// The PowerDNS schema has no notion of zone tree nodes, only flat rows
+// keyed by owner name, so descending to a child node is just extending
+// the owner name rather than navigating any real tree structure.
+struct DatabaseWriteNode {
+    dialect: SqlDialect,
+    txn: Arc<AsyncMutex<Option<sqlx::Transaction<'static, sqlx::Any>>>>,
+    domain_id: Arc<AsyncMutex<Option<i64>>>,
+    owner: StoredName,
+}
+
+//--- impl WritableZoneNode
+
+impl WritableZoneNode for DatabaseWriteNode {
+    fn update_child(
+        &self,
+        label: &Label,
+    ) -> Pin<
+        Box<dyn Future<Output = Result<Box<dyn WritableZoneNode>, io::Error>>>,
+    > {
+        let owner = label
+            .to_owned()
+            .into_chain(&self.owner)
+            .expect("child name stays within the zone")
+            .into();
+        let dialect = self.dialect;
+        let txn = self.txn.clone();
+        let domain_id = self.domain_id.clone();
+        Box::pin(async move {
+            Ok(Box::new(DatabaseWriteNode {
+                dialect,
+                txn,
+                domain_id,
+                owner,
+            }) as Box<dyn WritableZoneNode>)
+        })
+    }
+
+    fn update_rrset(
+        &self,
+        rrset: SharedRrset,
+    ) -> Pin<Box<dyn Future<Output = Result<(), io::Error>>>> {
+        let dialect = self.dialect;
+        let txn = self.txn.clone();
+        let domain_id = self.domain_id.clone();
+        let owner = self.owner.to_string();
+        Box::pin(async move {
+            let mut guard = txn.lock().await;
+            let conn = guard.as_mut().expect("open() was called first");
+            let id = domain_id.lock().await.expect("open() was called first");
+            let rtype = rrset.rtype();
+
+            sqlx::query(
+                &dialect.rewrite(r#"DELETE FROM records WHERE domain_id = ? AND name = ? AND type = ?"#),
+            )
+            .bind(id)
+            .bind(&owner)
+            .bind(rtype.to_string())
+            .execute(&mut **conn)
+            .await
+            .map_err(to_io_err)?;
+
+            for data in rrset.iter() {
+                sqlx::query(
+                    &dialect.rewrite(r#"INSERT INTO records (domain_id, name, type, content, ttl) VALUES (?, ?, ?, ?, ?)"#),
+                )
+                .bind(id)
+                .bind(&owner)
+                .bind(rtype.to_string())
+                .bind(data.to_string())
+                .bind(rrset.ttl().as_secs())
+                .execute(&mut **conn)
+                .await
+                .map_err(to_io_err)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    fn remove_rrset(
+        &self,
+        rtype: Rtype,
+    ) -> Pin<Box<dyn Future<Output = Result<(), io::Error>>>> {
+        let dialect = self.dialect;
+        let txn = self.txn.clone();
+        let domain_id = self.domain_id.clone();
+        let owner = self.owner.to_string();
+        Box::pin(async move {
+            let mut guard = txn.lock().await;
+            let conn = guard.as_mut().expect("open() was called first");
+            let id = domain_id.lock().await.expect("open() was called first");
+
+            sqlx::query(
+                &dialect.rewrite(r#"DELETE FROM records WHERE domain_id = ? AND name = ? AND type = ?"#),
+            )
+            .bind(id)
+            .bind(&owner)
+            .bind(rtype.to_string())
+            .execute(&mut **conn)
+            .await
+            .map_err(to_io_err)?;
+
+            Ok(())
+        })
+    }
+
+    fn remove_child(
+        &self,
+        label: &Label,
+    ) -> Pin<Box<dyn Future<Output = Result<(), io::Error>>>> {
+        let child_owner = label
+            .to_owned()
+            .into_chain(&self.owner)
+            .expect("child name stays within the zone")
+            .to_string();
+        let dialect = self.dialect;
+        let txn = self.txn.clone();
+        let domain_id = self.domain_id.clone();
+        Box::pin(async move {
+            let mut guard = txn.lock().await;
+            let conn = guard.as_mut().expect("open() was called first");
+            let id = domain_id.lock().await.expect("open() was called first");
+
+            // Delete the child owner and anything below it. DNS labels
+            // routinely contain `_` (`_dmarc`, `_sip._tcp`, DKIM
+            // selectors, ...), which is a single-character wildcard in SQL
+            // `LIKE`, so the owner name has to be escaped before it's used
+            // as a pattern or this would also delete unrelated rows that
+            // merely share its length and suffix.
+            sqlx::query(
+                &dialect.rewrite(r#"DELETE FROM records WHERE domain_id = ? AND (name = ? OR name LIKE ? ESCAPE '!')"#),
+            )
+            .bind(id)
+            .bind(&child_owner)
+            .bind(format!("%.{}", escape_like(&child_owner)))
+            .execute(&mut **conn)
+            .await
+            .map_err(to_io_err)?;
+
+            Ok(())
+        })
+    }
+
+    fn make_regular(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<(), io::Error>>>> {
+        // The PowerDNS schema has no notion of empty non-terminals, so
+        // there is nothing to clear when a node stops being one.
+        Box::pin(async { Ok(()) })
+    }
+}
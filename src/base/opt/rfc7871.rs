@@ -1,5 +1,7 @@
 //! EDNS Options from RFC 7871
 
+use core::fmt;
+
 use super::super::iana::OptionCode;
 use super::super::message_builder::OptBuilder;
 use super::super::net::IpAddr;
@@ -27,6 +29,28 @@ impl ClientSubnet {
         ClientSubnet { source_prefix_len, scope_prefix_len, addr }
     }
 
+    /// Fallibly creates a client subnet option.
+    ///
+    /// Unlike [`new`](Self::new), this rejects a `source_prefix_len` or
+    /// `scope_prefix_len` that exceeds what `addr`'s family can represent
+    /// (32 for an IPv4 address, 128 for an IPv6 address). `new` accepts
+    /// such combinations, but an option built that way composes into
+    /// wire data that `parse` rejects, so prefer this
+    /// constructor unless the lengths are already known to be in range.
+    pub fn try_new(
+        source_prefix_len: u8,
+        scope_prefix_len: u8,
+        addr: IpAddr
+    ) -> Result<ClientSubnet, LongPrefixLenError> {
+        let max_prefix_len = max_prefix_len(&addr);
+        if source_prefix_len > max_prefix_len
+            || scope_prefix_len > max_prefix_len
+        {
+            return Err(LongPrefixLenError);
+        }
+        Ok(ClientSubnet::new(source_prefix_len, scope_prefix_len, addr))
+    }
+
     pub fn push<Target: OctetsBuilder>(
         builder: &mut OptBuilder<Target>,
         source_prefix_len: u8,
@@ -41,6 +65,29 @@ impl ClientSubnet {
     pub fn addr(&self) -> IpAddr { self.addr }
 }
 
+/// The maximum valid prefix length for the family of `addr`: 32 for IPv4,
+/// 128 for IPv6.
+fn max_prefix_len(addr: &IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+//------------ LongPrefixLenError ---------------------------------------------
+
+/// A prefix length was longer than its address family allows.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LongPrefixLenError;
+
+impl fmt::Display for LongPrefixLenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(
+            "prefix length exceeds address family maximum"
+        )
+    }
+}
+
 
 //--- Parse and Compose
 
@@ -50,6 +97,32 @@ impl<Ref: AsRef<[u8]>> Parse<Ref> for ClientSubnet {
         let source_prefix_len = parser.parse_u8()?;
         let scope_prefix_len = parser.parse_u8()?;
 
+        // Reject a FAMILY/prefix-length combination that can't fit the
+        // address the FAMILY declares before even looking at ADDRESS: a
+        // SOURCE or SCOPE PREFIX-LENGTH above 32 for an IPv4 address (or
+        // above 128 for an IPv6 one). The ADDRESS length check below only
+        // sees the source length, never the scope length.
+        let max_prefix_len: u8 = match family {
+            1 => 32,
+            2 => 128,
+            _ => {
+                return Err(
+                    FormError::new(
+                        "invalid client subnet address family"
+                    ).into()
+                )
+            }
+        };
+        if source_prefix_len > max_prefix_len
+            || scope_prefix_len > max_prefix_len
+        {
+            return Err(
+                FormError::new(
+                    "client subnet prefix length exceeds address family maximum"
+                ).into()
+            );
+        }
+
         // https://tools.ietf.org/html/rfc7871#section-6
         //
         // | ADDRESS, variable number of octets, contains either an IPv4 or
@@ -70,8 +143,8 @@ impl<Ref: AsRef<[u8]>> Parse<Ref> for ClientSubnet {
                     );
                 }
                 parser.parse_buf(&mut buf[..prefix_bytes])?;
-                if let Some(e) = buf.get_mut(prefix_bytes - 1) {
-                    *e &= mask;
+                if let Some(i) = prefix_bytes.checked_sub(1) {
+                    buf[i] &= mask;
                 }
                 IpAddr::from(buf)
             }
@@ -85,18 +158,12 @@ impl<Ref: AsRef<[u8]>> Parse<Ref> for ClientSubnet {
                     );
                 }
                 parser.parse_buf(&mut buf[..prefix_bytes])?;
-                if let Some(e) = buf.get_mut(prefix_bytes - 1) {
-                    *e &= mask;
+                if let Some(i) = prefix_bytes.checked_sub(1) {
+                    buf[i] &= mask;
                 }
                 IpAddr::from(buf)
             }
-            _ => {
-                return Err(
-                    FormError::new(
-                        "invalid client subnet address family"
-                    ).into()
-                )
-            }
+            _ => unreachable!("family checked above"),
         };
         Ok(ClientSubnet::new(source_prefix_len, scope_prefix_len, addr))
     }
@@ -121,8 +188,9 @@ impl Compose for ClientSubnet {
                 self.source_prefix_len.compose(target)?;
                 self.scope_prefix_len.compose(target)?;
                 let mut array = addr.octets();
-                if let Some(e) = array.get_mut(prefix_bytes - 1) {
-                    *e &= mask;
+                let prefix_bytes = prefix_bytes.min(array.len());
+                if let Some(i) = prefix_bytes.checked_sub(1) {
+                    array[i] &= mask;
                 }
                 target.append_slice(&array[..prefix_bytes])
             }
@@ -131,8 +199,9 @@ impl Compose for ClientSubnet {
                 self.source_prefix_len.compose(target)?;
                 self.scope_prefix_len.compose(target)?;
                 let mut array = addr.octets();
-                if let Some(e) = array.get_mut(prefix_bytes - 1) {
-                    *e &= mask;
+                let prefix_bytes = prefix_bytes.min(array.len());
+                if let Some(i) = prefix_bytes.checked_sub(1) {
+                    array[i] &= mask;
                 }
                 target.append_slice(&array[..prefix_bytes])
             }
@@ -142,9 +211,15 @@ impl Compose for ClientSubnet {
 
 fn prefix_bytes(bits: usize) -> (usize, u8) {
     let n = (bits + 7) / 8;
-    let mask = match 8 - (bits % 8) {
+    // `bits % 8 == 0` covers every byte-aligned prefix length (0, 8, 16,
+    // ...), where the last included byte is wholly part of the prefix and
+    // so needs no masking. Matching on `8 - (bits % 8)` instead, as this
+    // used to, never actually lands on that case -- it computes 8, not 0,
+    // for a byte-aligned length -- and falls through to `0xff << 8`, which
+    // panics with a shift overflow in debug builds.
+    let mask = match bits % 8 {
         0 => 0xff,
-        n => 0xff << n,
+        r => 0xff << (8 - r),
     };
     (n, mask)
 }
@@ -186,4 +261,67 @@ mod tests {
         csub.compose(&mut buf).unwrap();
         assert_eq!(buf.as_ref(), [0, 1, 22, 0, 192, 0, 0].as_ref());
     }
+
+    #[test]
+    fn byte_aligned_prefix_len_round_trips() {
+        // /24 and the full /32 are both byte-aligned and used to panic
+        // with a shift overflow in `prefix_bytes`. Addresses below already
+        // have any bits beyond the prefix zeroed, so compose/parse is a
+        // lossless round trip.
+        for (source_prefix_len, addr) in
+            [(24, "192.0.2.0"), (32, "192.0.2.7")]
+        {
+            let csub =
+                ClientSubnet::new(source_prefix_len, 0, addr.parse().unwrap());
+            let mut buf = Octets512::new();
+            csub.compose(&mut buf).unwrap();
+
+            let parsed = ClientSubnet::parse(&mut Parser::from_ref(
+                buf.as_ref(),
+            ))
+            .unwrap();
+            assert_eq!(parsed, csub);
+        }
+    }
+
+    #[test]
+    fn zero_prefix_len_round_trips() {
+        // SOURCE PREFIX-LENGTH 0 is a normal RFC 7871 value meaning "no
+        // client subnet info", not malformed input. Being byte-aligned, it
+        // used to panic with a shift overflow in `prefix_bytes`, and the
+        // callers then computed `prefix_bytes - 1` below zero.
+        let csub = ClientSubnet::new(0, 0, "192.0.2.0".parse().unwrap());
+        let mut buf = Octets512::new();
+        csub.compose(&mut buf).unwrap();
+        assert_eq!(buf.as_ref(), [0, 1, 0, 0].as_ref());
+
+        let parsed =
+            ClientSubnet::parse(&mut Parser::from_ref(buf.as_ref())).unwrap();
+        assert_eq!(parsed.source_prefix_len(), 0);
+        assert_eq!(parsed.addr(), "0.0.0.0".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn try_new_rejects_out_of_range_prefix_len() {
+        let v4 = "192.0.2.0".parse().unwrap();
+        assert!(ClientSubnet::try_new(32, 0, v4).is_ok());
+        assert!(ClientSubnet::try_new(33, 0, v4).is_err());
+        assert!(ClientSubnet::try_new(0, 33, v4).is_err());
+
+        let v6 = "2001:db8::".parse().unwrap();
+        assert!(ClientSubnet::try_new(128, 0, v6).is_ok());
+        assert!(ClientSubnet::try_new(129, 0, v6).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_out_of_range_prefix_len() {
+        // FAMILY = 1 (IPv4), SOURCE PREFIX-LENGTH = 33, which can't fit a
+        // 32-bit address.
+        let opt_bytes = [0, 1, 33, 0, 192, 0, 2, 0];
+        let err = ClientSubnet::parse(&mut Parser::from_ref(
+            Octets512::try_from(opt_bytes.as_ref()).unwrap(),
+        ))
+        .unwrap_err();
+        assert!(matches!(err, ParseError::Form(_)));
+    }
 }